@@ -18,6 +18,11 @@
 //! This implementation supports a maximum of 65536 needles, each of which can be at most 65536
 //! bytes long. These requirements may be relaxed in the future.
 //!
+//! `TwoByteWM` is a single algorithm that works best on moderately sized sets of needles that are
+//! all fairly long. For other needle sets (a single needle, or a handful of single bytes), use
+//! [`Searcher`](enum.Searcher.html) instead: it picks the fastest backend for the needles you give
+//! it, falling back to `TwoByteWM` when nothing more specialized applies.
+//!
 //! # Example
 //! ```
 //! use wu_manber::{Match, TwoByteWM};
@@ -29,6 +34,7 @@
 //! ```
 
 use std::cmp::min;
+use std::io::{self, Read};
 
 #[cfg(test)]
 extern crate aho_corasick;
@@ -46,8 +52,8 @@ type NeedleIdx = u16;
 ///
 /// "Two-byte-wide" means that the search phase in the Wu-Manber algorithm uses spans of two bytes
 /// to look for potential matches.  This is suitable for moderately sized sets of needles; if there
-/// are too many needles then it might be faster to use spans of three bytes (but that isn't yet
-/// implemented by this crate).
+/// are too many needles then `ThreeByteWM`, which uses spans of three bytes, is likely to be
+/// faster.
 #[derive(Debug)]
 pub struct TwoByteWM {
     /// The needles that we are trying to match against, and their indices.
@@ -65,8 +71,25 @@ pub struct TwoByteWM {
     /// The minimimum length of any needle.
     pat_len: NByteIdx,
 
+    /// The maximum length of any needle.
+    ///
+    /// This is only used by `find_reader`, to decide how many trailing bytes of the stream must
+    /// be kept in the buffer across a refill so that a needle straddling the boundary is still
+    /// found.
+    max_len: NByteIdx,
+
+    /// The offset (from the start of a window of length `pat_len`) of the "critical" two-byte
+    /// block that we hash in order to look up `shift` and `hash`.
+    ///
+    /// This is chosen in `new` by picking, among all the allowed offsets, the one whose bytes are
+    /// rarest (according to `BYTE_RANK`) across the whole needle set.  Rare bytes make for a
+    /// sparser `shift` table, since a common block (e.g. one made of frequent bytes like spaces or
+    /// vowels) tends to occur at many positions across many needles and so forces `shift` down to
+    /// zero in a lot of places.
+    block_pos: NByteIdx,
+
     /// If `shift[HashFn(a, b)] = i` then no needle contains the two-byte string `ab` starting
-    /// anywhere between positions `pat_len - 2 - i` and `pat_len - 2`.
+    /// anywhere between positions `block_pos - i` and `block_pos`.
     ///
     /// Note that because this `Vec` can be quite long, we might save a substantial amount of space
     /// by shrinking the size of `NByteIdx`.
@@ -87,16 +110,53 @@ pub struct Match {
     pub pat_idx: usize,
 }
 
+/// Controls how a search breaks ties when more than one needle could match at the same position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Of the needles that match at a given position, report the shortest one. This is the
+    /// default used by `find`/`find_from`.
+    ShortestMatch,
+    /// Of the needles that match at a given position, report the longest one.
+    LeftmostLongest,
+}
+
 pub struct Matches<'a, 'b> {
     wm: &'a TwoByteWM,
     haystack: &'b [u8],
     cur_pos: usize,
+    kind: MatchKind,
 }
 
 impl<'a, 'b> Iterator for Matches<'a, 'b> {
     type Item = Match;
     fn next(&mut self) -> Option<Match> {
-        self.wm.find_from(self.haystack, self.cur_pos).map(|m| { self.cur_pos = m.end; m })
+        self.wm.find_from_kind(self.haystack, self.cur_pos, self.kind).map(|m| { self.cur_pos = m.end; m })
+    }
+}
+
+/// An iterator over every match (including overlapping ones); see `TwoByteWM::find_overlapping`.
+pub struct OverlappingMatches<'a, 'b> {
+    wm: &'a TwoByteWM,
+    haystack: &'b [u8],
+    /// The haystack position to resume scanning from once `pending` is drained.
+    pos: usize,
+    /// Matches found at the most recent zero-shift hit, not yet yielded to the caller.
+    pending: Vec<Match>,
+}
+
+impl<'a, 'b> Iterator for OverlappingMatches<'a, 'b> {
+    type Item = Match;
+    fn next(&mut self) -> Option<Match> {
+        if self.pending.is_empty() {
+            match self.wm.find_all_from(self.haystack, self.pos) {
+                Some((found, next_pos)) => {
+                    self.pos = next_pos;
+                    self.pending = found;
+                }
+                None => return None,
+            }
+        }
+        self.pending.pop()
     }
 }
 
@@ -108,6 +168,47 @@ fn hash_fn(a: u8, b: u8) -> NeedleIdx {
 
 const HASH_MAX: usize = (0xFFusize << 5) + 0xFF;
 
+/// Ranks every byte value by how rarely it tends to occur in typical text; `BYTE_RANK[b]` is
+/// lower for bytes that occur less often. This is the same idea used by `regex`'s literal
+/// searcher and `memchr`'s packed-pair prefilter: picking a critical block made of rare bytes
+/// keeps the `shift` table sparse, since common bytes would otherwise occur at many positions
+/// across many needles and force `shift` down to zero almost everywhere.
+static BYTE_RANK: [u8; 256] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 230, 231, 9, 10, 11, 12, 13,
+    14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29,
+    232, 233, 234, 30, 31, 32, 33, 235, 236, 237, 34, 35, 238, 239, 240, 36,
+    191, 192, 193, 194, 195, 196, 197, 198, 199, 200, 241, 242, 37, 38, 39, 243,
+    40, 226, 190, 208, 212, 244, 204, 202, 215, 221, 182, 188, 211, 205, 220, 223,
+    201, 181, 214, 216, 227, 209, 189, 206, 183, 203, 180, 41, 42, 43, 44, 45,
+    46, 253, 213, 228, 246, 255, 222, 218, 248, 251, 186, 207, 245, 224, 250, 252,
+    217, 185, 247, 249, 254, 229, 210, 225, 187, 219, 184, 47, 48, 49, 50, 51,
+    52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67,
+    68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83,
+    84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96, 97, 98, 99,
+    100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112, 113, 114, 115,
+    116, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127, 128, 129, 130, 131,
+    132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142, 143, 144, 145, 146, 147,
+    148, 149, 150, 151, 152, 153, 154, 155, 156, 157, 158, 159, 160, 161, 162, 163,
+    164, 165, 166, 167, 168, 169, 170, 171, 172, 173, 174, 175, 176, 177, 178, 179,
+];
+
+/// Picks the offset (from the start of a `pat_len`-byte window) of the two-byte block to hash,
+/// by choosing whichever offset has the rarest aggregate byte rank across all the needles.
+fn choose_block_pos(needles: &[Vec<u8>], pat_len: usize) -> NByteIdx {
+    let mut best_pos = 0;
+    let mut best_score = u64::max_value();
+    for k in 0..(pat_len - 1) {
+        let score: u64 = needles.iter()
+            .map(|p| BYTE_RANK[p[k] as usize] as u64 + BYTE_RANK[p[k + 1] as usize] as u64)
+            .sum();
+        if score < best_score {
+            best_score = score;
+            best_pos = k;
+        }
+    }
+    best_pos as NByteIdx
+}
+
 impl TwoByteWM {
     fn pat(&self, p_idx: NeedleIdx) -> &[u8] {
         &self.needles[p_idx as usize].1
@@ -131,14 +232,17 @@ impl TwoByteWM {
         }
 
         let pat_len = needles.iter().map(|p| p.len()).min().unwrap();
+        let max_len = needles.iter().map(|p| p.len()).max().unwrap();
         if pat_len < 2 {
             panic!("all needles must have length (in bytes) at least 2");
-        } else if pat_len > NByteIdx::max_value() as usize {
+        } else if max_len > NByteIdx::max_value() as usize {
             panic!("these needles are too long");
         }
         let pat_len = pat_len as NByteIdx;
+        let max_len = max_len as NByteIdx;
+        let block_pos = choose_block_pos(&needles, pat_len as usize);
 
-        let h = |p: &[u8]| hash_fn(p[(pat_len-2) as usize], p[(pat_len-1) as usize]);
+        let h = |p: &[u8]| hash_fn(p[block_pos as usize], p[(block_pos + 1) as usize]);
         let mut needles: Vec<_> = needles.into_iter().enumerate().collect();
         needles.sort_by(|p, q| h(&p.1).cmp(&h(&q.1)));
         let needles = needles;
@@ -155,11 +259,13 @@ impl TwoByteWM {
             }
         }
 
-        let mut shift = vec![pat_len - 1; HASH_MAX + 1];
+        let mut shift = vec![block_pos + 1; HASH_MAX + 1];
         for &(_, ref p) in &needles {
             for p_pos in 0..(pat_len - 1) {
-                let h = hash_fn(p[p_pos as usize], p[(p_pos + 1) as usize]);
-                shift[h as usize] = min(shift[h as usize], pat_len - p_pos - 2);
+                if p_pos <= block_pos {
+                    let h = hash_fn(p[p_pos as usize], p[(p_pos + 1) as usize]);
+                    shift[h as usize] = min(shift[h as usize], block_pos - p_pos);
+                }
             }
         }
 
@@ -167,38 +273,60 @@ impl TwoByteWM {
             needles: needles,
             prefix: prefix,
             pat_len: pat_len,
+            max_len: max_len,
+            block_pos: block_pos,
             shift: shift,
             hash: hash,
         }
     }
 
     /// Searches for a single match, starting from the given byte offset.
+    ///
+    /// If more than one needle could match at the same position, the shortest one is returned;
+    /// use `find_from_kind` to get the longest instead.
     pub fn find_from<P>(&self, haystack: P, offset: usize) -> Option<Match> where P: AsRef<[u8]> {
+        self.find_from_kind(haystack, offset, MatchKind::ShortestMatch)
+    }
+
+    /// Searches for a single match, starting from the given byte offset, using `kind` to break
+    /// ties when more than one needle could match at the same position.
+    pub fn find_from_kind<P>(&self, haystack: P, offset: usize, kind: MatchKind) -> Option<Match>
+            where P: AsRef<[u8]> {
         // `pos` points to the index in `haystack` that we are trying to align against the index
         // `pat_len - 1` of the needles.
         let pat_len = self.pat_len as usize;
+        let block_pos = self.block_pos as usize;
         let mut pos = offset + pat_len - 1;
         let haystack = haystack.as_ref();
-        while pos <= haystack.len() - 1 {
-            let h = hash_fn(haystack[pos - 1], haystack[pos]) as usize;
+        while pos < haystack.len() {
+            // Every expression below involving `pat_len` is written with the subtraction last
+            // (e.g. `pos + 1 - pat_len` rather than `pos - pat_len + 1`) so it can't underflow on
+            // the very first window, where `pos == pat_len - 1`.
+            let window_start = pos + 1 - pat_len;
+            let block_start = window_start + block_pos;
+            let h = hash_fn(haystack[block_start], haystack[block_start + 1]) as usize;
             let shift = self.shift[h] as usize;
             if shift == 0 {
                 // We might have matched the end of some needle.  Iterate over all the needles
                 // that we might have matched, and see if they match the beginning.
-                let a = haystack[pos - pat_len + 1];
-                let b = haystack[pos - pat_len + 2];
+                let a = haystack[window_start];
+                let b = haystack[window_start + 1];
                 let prefix = ((a as u16) << 8) + (b as u16);
                 let mut found: Option<NeedleIdx> = None;
                 for p_idx in self.hash[h]..self.hash[h+1] {
                     if self.prefix[p_idx as usize] == prefix {
                         // The prefix matches too, so now check for the full match.
                         let p = self.pat(p_idx);
-                        if haystack[(pos - pat_len + 1)..].starts_with(&p) {
+                        if haystack[window_start..].starts_with(&p) {
                             found = match found {
                                 None => Some(p_idx),
                                 Some(q_idx) => {
                                     let q = self.pat(q_idx);
-                                    Some(if p.len() < q.len() { p_idx } else { q_idx })
+                                    let p_wins = match kind {
+                                        MatchKind::ShortestMatch => p.len() < q.len(),
+                                        MatchKind::LeftmostLongest => p.len() > q.len(),
+                                    };
+                                    Some(if p_wins { p_idx } else { q_idx })
                                 }
                             }
                         }
@@ -206,8 +334,8 @@ impl TwoByteWM {
                 }
                 if let Some(p_idx) = found {
                     return Some(Match {
-                        start: pos - pat_len + 1,
-                        end: pos - pat_len + 1 + self.pat(p_idx).len(),
+                        start: window_start,
+                        end: window_start + self.pat(p_idx).len(),
                         pat_idx: self.pat_idx(p_idx),
                     })
                 }
@@ -221,20 +349,910 @@ impl TwoByteWM {
         None
     }
 
+    /// Like `find_from`, but every needle that matches starting at a given position is returned
+    /// together (rather than just the shortest), along with the haystack position immediately
+    /// after that starting position. This is the building block for `find_overlapping`, which
+    /// needs to know about every match that starts at a position, not just the best one.
+    fn find_all_from<P>(&self, haystack: P, offset: usize) -> Option<(Vec<Match>, usize)>
+            where P: AsRef<[u8]> {
+        let pat_len = self.pat_len as usize;
+        let block_pos = self.block_pos as usize;
+        let mut pos = offset + pat_len - 1;
+        let haystack = haystack.as_ref();
+        while pos < haystack.len() {
+            // Every expression below involving `pat_len` is written with the subtraction last
+            // (see the identical comment in `find_from_kind`).
+            let window_start = pos + 1 - pat_len;
+            let block_start = window_start + block_pos;
+            let h = hash_fn(haystack[block_start], haystack[block_start + 1]) as usize;
+            let shift = self.shift[h] as usize;
+            if shift == 0 {
+                let a = haystack[window_start];
+                let b = haystack[window_start + 1];
+                let prefix = ((a as u16) << 8) + (b as u16);
+                let mut found = Vec::new();
+                for p_idx in self.hash[h]..self.hash[h+1] {
+                    if self.prefix[p_idx as usize] == prefix {
+                        let p = self.pat(p_idx);
+                        if haystack[window_start..].starts_with(&p) {
+                            found.push(Match {
+                                start: window_start,
+                                end: window_start + p.len(),
+                                pat_idx: self.pat_idx(p_idx),
+                            });
+                        }
+                    }
+                }
+                if !found.is_empty() {
+                    // Advance by one window step (rather than to the end of any of the matches
+                    // just found), so that needles overlapping these ones aren't missed. This is
+                    // `window_start + 1` in byte-offset terms, i.e. the same units as `offset`.
+                    return Some((found, window_start + 1));
+                }
+                pos += 1;
+            } else {
+                pos += shift;
+            }
+        }
+
+        None
+    }
+
     /// Returns an iterator over non-overlapping matches.
     pub fn find<'a, 'b>(&'a self, haystack: &'b str) -> Matches<'a, 'b> {
+        self.find_with_kind(haystack, MatchKind::ShortestMatch)
+    }
+
+    /// Returns an iterator over non-overlapping matches, using `kind` to break ties when more
+    /// than one needle could match at the same position.
+    pub fn find_with_kind<'a, 'b>(&'a self, haystack: &'b str, kind: MatchKind) -> Matches<'a, 'b> {
         Matches {
             wm: &self,
             haystack: haystack.as_bytes(),
             cur_pos: 0,
+            kind: kind,
+        }
+    }
+
+    /// Returns an iterator over every match, including ones that overlap each other.
+    ///
+    /// Every needle that matches at a given starting position is reported, not just the shortest
+    /// one, so there is no `MatchKind` tie-break here; matches at different positions are reported
+    /// separately, even if they overlap.
+    pub fn find_overlapping<'a, 'b>(&'a self, haystack: &'b str) -> OverlappingMatches<'a, 'b> {
+        OverlappingMatches {
+            wm: self,
+            haystack: haystack.as_bytes(),
+            pos: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns an iterator over non-overlapping matches in a stream, without needing to buffer
+    /// the whole thing in memory up front.
+    ///
+    /// Internally, this keeps a rolling buffer that holds at least `max_len - 1` trailing bytes
+    /// across each refill, so a needle that straddles the boundary between two reads is still
+    /// found. Reported `Match` offsets are relative to the start of the stream, not to the
+    /// buffer.
+    ///
+    /// If the underlying reader returns an error, the iterator simply stops (as if the stream had
+    /// ended); there is currently no way to distinguish that from a clean end-of-stream.
+    pub fn find_reader<'a, R: Read>(&'a self, reader: R) -> ReaderMatches<'a, R> {
+        ReaderMatches {
+            wm: self,
+            reader: reader,
+            buf: Vec::new(),
+            buf_start: 0,
+            pos: 0,
+            eof: false,
+        }
+    }
+}
+
+/// An iterator over the matches found while reading from a stream; see `TwoByteWM::find_reader`.
+pub struct ReaderMatches<'a, R> {
+    wm: &'a TwoByteWM,
+    reader: R,
+    /// The bytes we've read so far but haven't yet been able to rule out of a potential match.
+    buf: Vec<u8>,
+    /// The absolute stream position corresponding to `buf[0]`.
+    buf_start: usize,
+    /// The offset into `buf` that the next search starts from: the end of the last match
+    /// reported, or the start of `buf` if that match has since been dropped from it.
+    pos: usize,
+    /// Whether the underlying reader has reported end-of-stream (or an error).
+    eof: bool,
+}
+
+impl<'a, R: Read> ReaderMatches<'a, R> {
+    /// Reads more data into `buf`, first dropping all but the last `max_len - 1` bytes (the most
+    /// that a still-unmatched needle could need from before the refill).
+    fn refill(&mut self) {
+        let keep = min(self.buf.len(), self.wm.max_len as usize - 1);
+        let drop = self.buf.len() - keep;
+        self.buf.drain(..drop);
+        self.buf_start += drop;
+        self.pos = self.pos.saturating_sub(drop);
+
+        let mut chunk = [0u8; 8 * 1024];
+        loop {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => { self.eof = true; return; }
+                Ok(n) => { self.buf.extend_from_slice(&chunk[..n]); return; }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => { self.eof = true; return; }
+            }
+        }
+    }
+}
+
+impl<'a, R: Read> Iterator for ReaderMatches<'a, R> {
+    type Item = Match;
+    fn next(&mut self) -> Option<Match> {
+        loop {
+            if !self.buf.is_empty() {
+                if let Some(m) = self.wm.find_from(&self.buf[..], self.pos) {
+                    // A match starting within `max_len` bytes of the end of `buf` may overlap a
+                    // needle that starts earlier but whose tail hasn't been read yet, so it is
+                    // only reported once enough bytes follow it (or the stream has ended).
+                    if self.eof || m.start + self.wm.max_len as usize <= self.buf.len() {
+                        self.pos = m.end;
+                        return Some(Match {
+                            start: m.start + self.buf_start,
+                            end: m.end + self.buf_start,
+                            pat_idx: m.pat_idx,
+                        });
+                    }
+                }
+            }
+            if self.eof {
+                return None;
+            }
+            self.refill();
+        }
+    }
+}
+
+/// For now, we default to this hash function for three-byte blocks, by extension of `hash_fn`.
+fn hash_fn3(a: u8, b: u8, c: u8) -> u32 {
+    ((a as u32) << 10) + ((b as u32) << 5) + (c as u32)
+}
+
+const HASH_MAX3: usize = (0xFFusize << 10) + (0xFFusize << 5) + 0xFF;
+
+/// Picks the offset (from the start of a `pat_len`-byte window) of the three-byte block to hash,
+/// by choosing whichever offset has the rarest aggregate byte rank across all the needles.
+fn choose_block_pos3(needles: &[Vec<u8>], pat_len: usize) -> NByteIdx {
+    let mut best_pos = 0;
+    let mut best_score = u64::max_value();
+    for k in 0..(pat_len - 2) {
+        let score: u64 = needles.iter()
+            .map(|p| BYTE_RANK[p[k] as usize] as u64
+                     + BYTE_RANK[p[k + 1] as usize] as u64
+                     + BYTE_RANK[p[k + 2] as usize] as u64)
+            .sum();
+        if score < best_score {
+            best_score = score;
+            best_pos = k;
+        }
+    }
+    best_pos as NByteIdx
+}
+
+/// `ThreeByteWM` is the width-3 counterpart to `TwoByteWM`: it hashes spans of three bytes rather
+/// than two.
+///
+/// As the number of needles grows, a two-byte `shift` table saturates -- there are only
+/// 65536 possible two-byte blocks, so with enough needles almost every block is shared by some
+/// needle and `shift` collapses to zero almost everywhere, degrading the skip loop to a
+/// byte-by-byte scan. A three-byte block has 256 times as many possible values, so `shift` stays
+/// meaningful for much larger needle sets. `Searcher` picks this automatically once the needle
+/// count crosses `THREE_BYTE_THRESHOLD`, provided every needle is at least three bytes long.
+#[derive(Debug)]
+pub struct ThreeByteWM {
+    /// The needles that we are trying to match against, and their indices.
+    ///
+    /// Each of the needles has length (in bytes) at least 3.  They are sorted in increasing order
+    /// of the hash value of their three critical bytes.
+    needles: Vec<(usize, Vec<u8>)>,
+
+    /// For each of the needles above, this contains the first two bytes, concatenated into a
+    /// `u16`.
+    ///
+    /// This `Vec` is indexed in the same way as `needles`.
+    prefix: Vec<u16>,
+
+    /// The minimimum length of any needle.
+    pat_len: NByteIdx,
+
+    /// The offset (from the start of a window of length `pat_len`) of the "critical" three-byte
+    /// block that we hash in order to look up `shift` and `hash`. See `TwoByteWM::block_pos` for
+    /// the rationale.
+    block_pos: NByteIdx,
+
+    /// If `shift[HashFn3(a, b, c)] = i` then no needle contains the three-byte string `abc`
+    /// starting anywhere between positions `block_pos - i` and `block_pos`.
+    shift: Vec<NByteIdx>,
+
+    /// If `hash[HashFn3(a, b, c)] = i` then the needles whose critical bytes hash to
+    /// `HashFn3(a, b, c)` begin at `needles[i]`.
+    hash: Vec<NeedleIdx>,
+}
+
+impl ThreeByteWM {
+    fn pat(&self, p_idx: NeedleIdx) -> &[u8] {
+        &self.needles[p_idx as usize].1
+    }
+
+    fn pat_idx(&self, p_idx: NeedleIdx) -> usize {
+        self.needles[p_idx as usize].0
+    }
+
+    /// Creates lookup tables to efficiently search for the given needles.
+    ///
+    /// The order of `needles` is significant, since all `Match`es returned from this
+    /// `ThreeByteWM` will include an index into `needles` saying which needle matched.
+    pub fn new<I, P>(needles: I) -> ThreeByteWM
+            where P: AsRef<[u8]>, I: IntoIterator<Item=P> {
+        let needles: Vec<_> = needles.into_iter().map(|s| s.as_ref().to_vec()).collect();
+        if needles.is_empty() {
+            panic!("cannot create ThreeByteWM from an empty set of needles");
+        } else if needles.len() > NeedleIdx::max_value() as usize {
+            panic!("too many needles");
+        }
+
+        let pat_len = needles.iter().map(|p| p.len()).min().unwrap();
+        if pat_len < 3 {
+            panic!("all needles must have length (in bytes) at least 3");
+        } else if pat_len > NByteIdx::max_value() as usize {
+            panic!("these needles are too long");
+        }
+        let pat_len = pat_len as NByteIdx;
+        let block_pos = choose_block_pos3(&needles, pat_len as usize);
+
+        let h = |p: &[u8]| hash_fn3(p[block_pos as usize], p[(block_pos + 1) as usize],
+                                     p[(block_pos + 2) as usize]);
+        let mut needles: Vec<_> = needles.into_iter().enumerate().collect();
+        needles.sort_by(|p, q| h(&p.1).cmp(&h(&q.1)));
+        let needles = needles;
+        let prefix: Vec<_> = needles.iter()
+            .map(|p| ((p.1[0] as u16) << 8) + (p.1[1] as u16))
+            .collect();
+
+        let mut hash = vec![0; HASH_MAX3 + 2];
+        for (p_idx, &(_, ref p)) in needles.iter().enumerate().rev() {
+            let h_idx = h(&p) as usize;
+            hash[h_idx] = p_idx as NeedleIdx;
+            if hash[h_idx + 1] == 0 {
+                hash[h_idx + 1] = p_idx as NeedleIdx + 1;
+            }
+        }
+
+        let mut shift = vec![block_pos + 1; HASH_MAX3 + 1];
+        for &(_, ref p) in &needles {
+            for p_pos in 0..(pat_len - 2) {
+                if p_pos <= block_pos {
+                    let h = hash_fn3(p[p_pos as usize], p[(p_pos + 1) as usize], p[(p_pos + 2) as usize]);
+                    shift[h as usize] = min(shift[h as usize], block_pos - p_pos);
+                }
+            }
+        }
+
+        ThreeByteWM {
+            needles: needles,
+            prefix: prefix,
+            pat_len: pat_len,
+            block_pos: block_pos,
+            shift: shift,
+            hash: hash,
+        }
+    }
+
+    /// Searches for a single match, starting from the given byte offset.
+    ///
+    /// If more than one needle could match at the same position, the shortest one is returned;
+    /// use `find_from_kind` to get the longest instead.
+    pub fn find_from<P>(&self, haystack: P, offset: usize) -> Option<Match> where P: AsRef<[u8]> {
+        self.find_from_kind(haystack, offset, MatchKind::ShortestMatch)
+    }
+
+    /// Searches for a single match, starting from the given byte offset, using `kind` to break
+    /// ties when more than one needle could match at the same position.
+    pub fn find_from_kind<P>(&self, haystack: P, offset: usize, kind: MatchKind) -> Option<Match>
+            where P: AsRef<[u8]> {
+        let pat_len = self.pat_len as usize;
+        let block_pos = self.block_pos as usize;
+        let mut pos = offset + pat_len - 1;
+        let haystack = haystack.as_ref();
+        while pos < haystack.len() {
+            // Every expression below involving `pat_len` is written with the subtraction last
+            // (see the identical comment in `TwoByteWM::find_from_kind`).
+            let window_start = pos + 1 - pat_len;
+            let block_start = window_start + block_pos;
+            let h = hash_fn3(haystack[block_start], haystack[block_start + 1],
+                              haystack[block_start + 2]) as usize;
+            let shift = self.shift[h] as usize;
+            if shift == 0 {
+                let a = haystack[window_start];
+                let b = haystack[window_start + 1];
+                let prefix = ((a as u16) << 8) + (b as u16);
+                let mut found: Option<NeedleIdx> = None;
+                for p_idx in self.hash[h]..self.hash[h+1] {
+                    if self.prefix[p_idx as usize] == prefix {
+                        let p = self.pat(p_idx);
+                        if haystack[window_start..].starts_with(&p) {
+                            found = match found {
+                                None => Some(p_idx),
+                                Some(q_idx) => {
+                                    let q = self.pat(q_idx);
+                                    let p_wins = match kind {
+                                        MatchKind::ShortestMatch => p.len() < q.len(),
+                                        MatchKind::LeftmostLongest => p.len() > q.len(),
+                                    };
+                                    Some(if p_wins { p_idx } else { q_idx })
+                                }
+                            }
+                        }
+                    }
+                }
+                if let Some(p_idx) = found {
+                    return Some(Match {
+                        start: window_start,
+                        end: window_start + self.pat(p_idx).len(),
+                        pat_idx: self.pat_idx(p_idx),
+                    })
+                }
+
+                pos += 1;
+            } else {
+                pos += shift;
+            }
+        }
+
+        None
+    }
+
+    /// Like `find_from`, but every needle that matches starting at a given position is returned
+    /// together (rather than just the shortest), along with the haystack position immediately
+    /// after that starting position. This is the building block for `find_overlapping`, which
+    /// needs to know about every match that starts at a position, not just the best one.
+    fn find_all_from<P>(&self, haystack: P, offset: usize) -> Option<(Vec<Match>, usize)>
+            where P: AsRef<[u8]> {
+        let pat_len = self.pat_len as usize;
+        let block_pos = self.block_pos as usize;
+        let mut pos = offset + pat_len - 1;
+        let haystack = haystack.as_ref();
+        while pos < haystack.len() {
+            let window_start = pos + 1 - pat_len;
+            let block_start = window_start + block_pos;
+            let h = hash_fn3(haystack[block_start], haystack[block_start + 1],
+                              haystack[block_start + 2]) as usize;
+            let shift = self.shift[h] as usize;
+            if shift == 0 {
+                let a = haystack[window_start];
+                let b = haystack[window_start + 1];
+                let prefix = ((a as u16) << 8) + (b as u16);
+                let mut found = Vec::new();
+                for p_idx in self.hash[h]..self.hash[h+1] {
+                    if self.prefix[p_idx as usize] == prefix {
+                        let p = self.pat(p_idx);
+                        if haystack[window_start..].starts_with(&p) {
+                            found.push(Match {
+                                start: window_start,
+                                end: window_start + p.len(),
+                                pat_idx: self.pat_idx(p_idx),
+                            });
+                        }
+                    }
+                }
+                if !found.is_empty() {
+                    // Advance by one window step (see the identical comment in
+                    // `TwoByteWM::find_all_from`).
+                    return Some((found, window_start + 1));
+                }
+                pos += 1;
+            } else {
+                pos += shift;
+            }
+        }
+
+        None
+    }
+
+    /// Returns an iterator over non-overlapping matches.
+    pub fn find<'a, 'b>(&'a self, haystack: &'b str) -> ThreeByteMatches<'a, 'b> {
+        self.find_with_kind(haystack, MatchKind::ShortestMatch)
+    }
+
+    /// Returns an iterator over non-overlapping matches, using `kind` to break ties when more
+    /// than one needle could match at the same position.
+    pub fn find_with_kind<'a, 'b>(&'a self, haystack: &'b str, kind: MatchKind) -> ThreeByteMatches<'a, 'b> {
+        ThreeByteMatches {
+            wm: &self,
+            haystack: haystack.as_bytes(),
+            cur_pos: 0,
+            kind: kind,
+        }
+    }
+
+    /// Returns an iterator over every match, including ones that overlap each other.
+    pub fn find_overlapping<'a, 'b>(&'a self, haystack: &'b str) -> ThreeByteOverlappingMatches<'a, 'b> {
+        ThreeByteOverlappingMatches {
+            wm: self,
+            haystack: haystack.as_bytes(),
+            pos: 0,
+            pending: Vec::new(),
+        }
+    }
+}
+
+pub struct ThreeByteMatches<'a, 'b> {
+    wm: &'a ThreeByteWM,
+    haystack: &'b [u8],
+    cur_pos: usize,
+    kind: MatchKind,
+}
+
+impl<'a, 'b> Iterator for ThreeByteMatches<'a, 'b> {
+    type Item = Match;
+    fn next(&mut self) -> Option<Match> {
+        self.wm.find_from_kind(self.haystack, self.cur_pos, self.kind).map(|m| { self.cur_pos = m.end; m })
+    }
+}
+
+/// An iterator over every match (including overlapping ones); see `ThreeByteWM::find_overlapping`.
+pub struct ThreeByteOverlappingMatches<'a, 'b> {
+    wm: &'a ThreeByteWM,
+    haystack: &'b [u8],
+    /// The haystack position to resume scanning from once `pending` is drained.
+    pos: usize,
+    /// Matches found at the most recent zero-shift hit, not yet yielded to the caller.
+    pending: Vec<Match>,
+}
+
+impl<'a, 'b> Iterator for ThreeByteOverlappingMatches<'a, 'b> {
+    type Item = Match;
+    fn next(&mut self) -> Option<Match> {
+        if self.pending.is_empty() {
+            match self.wm.find_all_from(self.haystack, self.pos) {
+                Some((found, next_pos)) => {
+                    self.pos = next_pos;
+                    self.pending = found;
+                }
+                None => return None,
+            }
+        }
+        self.pending.pop()
+    }
+}
+
+/// A SIMD "packed pair" prefilter, modeled on the generic-SIMD searcher in `memchr`.
+///
+/// It picks two offsets within the needle -- preferring rare bytes, so the filter fires less
+/// often -- broadcasts the needle's bytes at those offsets into SIMD lanes, and scans the
+/// haystack for positions where both lanes match simultaneously. Those positions are only
+/// *candidates*; the caller still has to verify the rest of the needle.
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::*;
+    use ::BYTE_RANK;
+
+    const WIDTH: usize = 16; // an SSE2 vector holds 16 bytes.
+
+    #[derive(Debug)]
+    pub struct PackedPair {
+        index1: usize,
+        index2: usize,
+        byte1: u8,
+        byte2: u8,
+    }
+
+    impl PackedPair {
+        /// Returns `None` if `needle` is too short to support a packed-pair filter.
+        pub fn new(needle: &[u8]) -> Option<PackedPair> {
+            if needle.len() < 2 {
+                return None;
+            }
+            let rank = |b: u8| BYTE_RANK[b as usize];
+            let mut index1 = 0;
+            let mut index2 = 1;
+            if rank(needle[index2]) < rank(needle[index1]) {
+                index1 = 1;
+                index2 = 0;
+            }
+            for i in 2..needle.len() {
+                let b = needle[i];
+                if rank(b) < rank(needle[index1]) {
+                    index2 = index1;
+                    index1 = i;
+                } else if rank(b) < rank(needle[index2]) {
+                    index2 = i;
+                }
+            }
+            if index1 > index2 {
+                let tmp = index1;
+                index1 = index2;
+                index2 = tmp;
+            }
+            Some(PackedPair {
+                index1: index1,
+                index2: index2,
+                byte1: needle[index1],
+                byte2: needle[index2],
+            })
+        }
+
+        /// Returns the position of the next candidate at or after `pos`: a position `i` such that
+        /// `haystack[i + index1] == byte1` and `haystack[i + index2] == byte2`.
+        pub fn find_candidate(&self, haystack: &[u8], pos: usize) -> Option<usize> {
+            let min_len = self.index2 + 1;
+            if pos + min_len > haystack.len() {
+                return None;
+            }
+
+            let mut i = pos;
+            // SSE2 is part of the x86_64 baseline, so no runtime feature check is needed.
+            unsafe {
+                let v1 = _mm_set1_epi8(self.byte1 as i8);
+                let v2 = _mm_set1_epi8(self.byte2 as i8);
+                while i + self.index2 + WIDTH <= haystack.len() {
+                    let h1 = _mm_loadu_si128(haystack.as_ptr().add(i + self.index1) as *const __m128i);
+                    let h2 = _mm_loadu_si128(haystack.as_ptr().add(i + self.index2) as *const __m128i);
+                    let eq = _mm_and_si128(_mm_cmpeq_epi8(h1, v1), _mm_cmpeq_epi8(h2, v2));
+                    let mask = _mm_movemask_epi8(eq);
+                    if mask != 0 {
+                        return Some(i + mask.trailing_zeros() as usize);
+                    }
+                    i += WIDTH;
+                }
+            }
+            // The remaining haystack is shorter than one vector; finish with a scalar scan.
+            while i + min_len <= haystack.len() {
+                if haystack[i + self.index1] == self.byte1 && haystack[i + self.index2] == self.byte2 {
+                    return Some(i);
+                }
+                i += 1;
+            }
+            None
+        }
+    }
+}
+
+/// A scalar stand-in for [`simd::PackedPair`](struct.PackedPair.html) on platforms without an
+/// SSE2 implementation; `new` always returns `None`, so callers always fall back to the scalar
+/// search.
+#[cfg(not(target_arch = "x86_64"))]
+mod simd {
+    #[derive(Debug)]
+    pub struct PackedPair;
+
+    impl PackedPair {
+        pub fn new(_needle: &[u8]) -> Option<PackedPair> {
+            None
+        }
+
+        pub fn find_candidate(&self, _haystack: &[u8], _pos: usize) -> Option<usize> {
+            None
+        }
+    }
+}
+
+/// A single-needle searcher, using a SIMD packed-pair prefilter where one is available (see the
+/// `simd` module) and the Boyer-Moore-Horspool algorithm otherwise.
+///
+/// This is faster than `TwoByteWM` when there is only one needle, since there's no need to pay
+/// for `TwoByteWM`'s hash/shift tables (which are sized for the worst case over every needle) or
+/// its needle-disambiguation logic.
+#[derive(Debug)]
+pub struct SingleSearcher {
+    needle: Vec<u8>,
+
+    /// `bad_char_shift[b]` is how far we can safely move the window forward if `b` is the last
+    /// byte of the window but doesn't lead to a match; it is `needle.len()` if `b` doesn't occur
+    /// in `needle` at all (other than possibly as the last byte).
+    ///
+    /// This is indexed by byte value, so it always has length 256.
+    bad_char_shift: Vec<usize>,
+
+    /// A SIMD prefilter for quickly skipping past positions that can't possibly match, or `None`
+    /// if no SIMD implementation is available for this target or the needle is a single byte (in
+    /// which case `find_from` uses `bad_char_shift` instead).
+    prefilter: Option<simd::PackedPair>,
+}
+
+impl SingleSearcher {
+    /// Builds a `SingleSearcher` for the given needle; panics if it is empty.
+    pub fn new(needle: Vec<u8>) -> SingleSearcher {
+        if needle.is_empty() {
+            panic!("cannot create a SingleSearcher from an empty needle");
+        }
+        let mut bad_char_shift = vec![needle.len(); 256];
+        for (i, &b) in needle[..needle.len() - 1].iter().enumerate() {
+            bad_char_shift[b as usize] = needle.len() - 1 - i;
+        }
+        let prefilter = simd::PackedPair::new(&needle);
+        SingleSearcher { needle: needle, bad_char_shift: bad_char_shift, prefilter: prefilter }
+    }
+
+    /// Searches for the needle, starting from the given byte offset.
+    pub fn find_from(&self, haystack: &[u8], offset: usize) -> Option<Match> {
+        let n = self.needle.len();
+        if let Some(ref prefilter) = self.prefilter {
+            let mut pos = offset;
+            while let Some(cand) = prefilter.find_candidate(haystack, pos) {
+                if cand + n <= haystack.len() && &haystack[cand..cand + n] == &self.needle[..] {
+                    return Some(Match { start: cand, end: cand + n, pat_idx: 0 });
+                }
+                pos = cand + 1;
+            }
+            return None;
+        }
+
+        let mut pos = offset;
+        while pos + n <= haystack.len() {
+            if &haystack[pos..pos + n] == &self.needle[..] {
+                return Some(Match { start: pos, end: pos + n, pat_idx: 0 });
+            }
+            pos += self.bad_char_shift[haystack[pos + n - 1] as usize];
+        }
+        None
+    }
+}
+
+/// A searcher for a handful of single-byte needles, using a 256-entry membership table.
+#[derive(Debug)]
+pub struct ByteSetSearcher {
+    /// Maps a byte value to the index (into the original needle list) of the needle consisting
+    /// of that byte, or `-1` if no needle is that byte.
+    ///
+    /// This is indexed by byte value, so it always has length 256.
+    which: Vec<i32>,
+}
+
+impl ByteSetSearcher {
+    /// Builds a `ByteSetSearcher` for the given single-byte needles.
+    pub fn new(needles: &[u8]) -> ByteSetSearcher {
+        let mut which = vec![-1i32; 256];
+        for (i, &b) in needles.iter().enumerate() {
+            if which[b as usize] == -1 {
+                which[b as usize] = i as i32;
+            }
+        }
+        ByteSetSearcher { which: which }
+    }
+
+    /// Searches for any of the needles, starting from the given byte offset.
+    pub fn find_from(&self, haystack: &[u8], offset: usize) -> Option<Match> {
+        for pos in offset..haystack.len() {
+            let i = self.which[haystack[pos] as usize];
+            if i >= 0 {
+                return Some(Match { start: pos, end: pos + 1, pat_idx: i as usize });
+            }
+        }
+        None
+    }
+}
+
+/// Picks which backend a `Searcher` should use for a given needle set.
+///
+/// `Auto` (the default, via `Searcher::new`) inspects the needles and picks the backend that is
+/// expected to be fastest; the other variants force a particular backend regardless, which is
+/// mostly useful for testing and benchmarking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Auto,
+    Single,
+    ByteSet,
+    TwoByte,
+    ThreeByte,
+}
+
+/// `Searcher` is a multi-pattern search front end that dispatches to whichever backend is
+/// expected to be fastest for the given needle set, in the same spirit as `regex`'s internal
+/// literal matcher: a single needle gets a dedicated substring search, a handful of single-byte
+/// needles get a byte-set scan, moderately sized needle sets use `TwoByteWM`, and very large
+/// sets of needles that are all at least three bytes long use `ThreeByteWM`.
+#[derive(Debug)]
+pub enum Searcher {
+    Single(SingleSearcher),
+    ByteSet(ByteSetSearcher),
+    TwoByte(TwoByteWM),
+    ThreeByte(ThreeByteWM),
+}
+
+/// Above this many single-byte needles, `Searcher::new` prefers `TwoByteWM` to `ByteSetSearcher`;
+/// in practice a `ByteSetSearcher` this big is rare, but it keeps `Backend::Auto` from ever being
+/// a pessimization.
+const BYTE_SET_MAX: usize = 64;
+
+/// Above this many needles, `Searcher::new` prefers `ThreeByteWM` to `TwoByteWM`, since a
+/// two-byte `shift` table saturates (see `ThreeByteWM`'s docs) long before a needle set gets this
+/// big.
+const THREE_BYTE_THRESHOLD: usize = 512;
+
+impl Searcher {
+    /// Builds a `Searcher` for the given needles, automatically picking the backend expected to
+    /// be fastest. Use `Searcher::with_backend` to override this choice.
+    pub fn new<I, P>(needles: I) -> Searcher where P: AsRef<[u8]>, I: IntoIterator<Item=P> {
+        Searcher::with_backend(needles, Backend::Auto)
+    }
+
+    /// Builds a `Searcher` for the given needles, using the requested `backend`.
+    ///
+    /// Panics if `backend` is incompatible with the needles (for example, `Backend::ByteSet` with
+    /// a needle that isn't exactly one byte long, or `Backend::TwoByte`/`Backend::ThreeByte` with
+    /// a needle shorter than two/three bytes -- see `TwoByteWM::new` and `ThreeByteWM::new`).
+    pub fn with_backend<I, P>(needles: I, backend: Backend) -> Searcher
+            where P: AsRef<[u8]>, I: IntoIterator<Item=P> {
+        let needles: Vec<Vec<u8>> = needles.into_iter().map(|s| s.as_ref().to_vec()).collect();
+        let all_single_bytes = needles.iter().all(|n| n.len() == 1);
+        let min_len = needles.iter().map(|n| n.len()).min().unwrap_or(0);
+
+        let backend = if backend == Backend::Auto {
+            if needles.len() == 1 {
+                Backend::Single
+            } else if all_single_bytes && needles.len() <= BYTE_SET_MAX {
+                Backend::ByteSet
+            } else if needles.len() > THREE_BYTE_THRESHOLD && min_len >= 3 {
+                Backend::ThreeByte
+            } else {
+                Backend::TwoByte
+            }
+        } else {
+            backend
+        };
+
+        match backend {
+            Backend::Auto => unreachable!(),
+            Backend::Single => {
+                if needles.len() != 1 {
+                    panic!("Backend::Single requires exactly one needle");
+                }
+                Searcher::Single(SingleSearcher::new(needles.into_iter().next().unwrap()))
+            }
+            Backend::ByteSet => {
+                if !all_single_bytes {
+                    panic!("Backend::ByteSet requires every needle to be one byte long");
+                }
+                let bytes: Vec<u8> = needles.iter().map(|n| n[0]).collect();
+                Searcher::ByteSet(ByteSetSearcher::new(&bytes))
+            }
+            Backend::TwoByte => Searcher::TwoByte(TwoByteWM::new(needles)),
+            Backend::ThreeByte => Searcher::ThreeByte(ThreeByteWM::new(needles)),
+        }
+    }
+
+    /// Searches for a single match, starting from the given byte offset.
+    ///
+    /// If more than one needle could match at the same position, the shortest one is returned;
+    /// use `find_from_kind` to get the longest instead.
+    pub fn find_from<P>(&self, haystack: P, offset: usize) -> Option<Match> where P: AsRef<[u8]> {
+        self.find_from_kind(haystack, offset, MatchKind::ShortestMatch)
+    }
+
+    /// Searches for a single match, starting from the given byte offset, using `kind` to break
+    /// ties when more than one needle could match at the same position.
+    ///
+    /// `Backend::Single` and `Backend::ByteSet` never have more than one needle that could match
+    /// at the same position, so `kind` has no effect on them.
+    pub fn find_from_kind<P>(&self, haystack: P, offset: usize, kind: MatchKind) -> Option<Match>
+            where P: AsRef<[u8]> {
+        match *self {
+            Searcher::Single(ref s) => s.find_from(haystack.as_ref(), offset),
+            Searcher::ByteSet(ref s) => s.find_from(haystack.as_ref(), offset),
+            Searcher::TwoByte(ref s) => s.find_from_kind(haystack, offset, kind),
+            Searcher::ThreeByte(ref s) => s.find_from_kind(haystack, offset, kind),
+        }
+    }
+
+    /// Returns an iterator over non-overlapping matches.
+    pub fn find<'a, 'b>(&'a self, haystack: &'b str) -> SearcherMatches<'a, 'b> {
+        self.find_with_kind(haystack, MatchKind::ShortestMatch)
+    }
+
+    /// Returns an iterator over non-overlapping matches, using `kind` to break ties when more
+    /// than one needle could match at the same position.
+    pub fn find_with_kind<'a, 'b>(&'a self, haystack: &'b str, kind: MatchKind) -> SearcherMatches<'a, 'b> {
+        SearcherMatches {
+            searcher: &self,
+            haystack: haystack.as_bytes(),
+            cur_pos: 0,
+            kind: kind,
+        }
+    }
+
+    /// Returns an iterator over every match, including ones that overlap each other.
+    pub fn find_overlapping<'a, 'b>(&'a self, haystack: &'b str) -> SearcherOverlappingMatches<'a, 'b> {
+        match *self {
+            Searcher::Single(ref s) => SearcherOverlappingMatches::Single {
+                s: s,
+                haystack: haystack.as_bytes(),
+                pos: 0,
+            },
+            Searcher::ByteSet(ref s) => SearcherOverlappingMatches::ByteSet {
+                s: s,
+                haystack: haystack.as_bytes(),
+                pos: 0,
+            },
+            Searcher::TwoByte(ref s) => SearcherOverlappingMatches::TwoByte(s.find_overlapping(haystack)),
+            Searcher::ThreeByte(ref s) => SearcherOverlappingMatches::ThreeByte(s.find_overlapping(haystack)),
+        }
+    }
+}
+
+pub struct SearcherMatches<'a, 'b> {
+    searcher: &'a Searcher,
+    haystack: &'b [u8],
+    cur_pos: usize,
+    kind: MatchKind,
+}
+
+impl<'a, 'b> Iterator for SearcherMatches<'a, 'b> {
+    type Item = Match;
+    fn next(&mut self) -> Option<Match> {
+        self.searcher.find_from_kind(self.haystack, self.cur_pos, self.kind).map(|m| { self.cur_pos = m.end; m })
+    }
+}
+
+/// An iterator over every match (including overlapping ones); see `Searcher::find_overlapping`.
+///
+/// For `Backend::Single`, this re-searches starting one byte after the previous match's start, so
+/// a needle that overlaps itself (e.g. `"aa"` in `"aaaa"`) is still found. For `Backend::ByteSet`,
+/// matches are always one byte long and can never overlap, so this behaves the same as `find`.
+pub enum SearcherOverlappingMatches<'a, 'b> {
+    Single { s: &'a SingleSearcher, haystack: &'b [u8], pos: usize },
+    ByteSet { s: &'a ByteSetSearcher, haystack: &'b [u8], pos: usize },
+    TwoByte(OverlappingMatches<'a, 'b>),
+    ThreeByte(ThreeByteOverlappingMatches<'a, 'b>),
+}
+
+impl<'a, 'b> Iterator for SearcherOverlappingMatches<'a, 'b> {
+    type Item = Match;
+    fn next(&mut self) -> Option<Match> {
+        match *self {
+            SearcherOverlappingMatches::Single { ref s, ref haystack, ref mut pos } => {
+                let found = s.find_from(haystack, *pos);
+                if let Some(ref m) = found {
+                    *pos = m.start + 1;
+                }
+                found
+            }
+            SearcherOverlappingMatches::ByteSet { ref s, ref haystack, ref mut pos } => {
+                let found = s.find_from(haystack, *pos);
+                if let Some(ref m) = found {
+                    *pos = m.end;
+                }
+                found
+            }
+            SearcherOverlappingMatches::TwoByte(ref mut it) => it.next(),
+            SearcherOverlappingMatches::ThreeByte(ref mut it) => it.next(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use ::{Match, TwoByteWM};
+    use ::{Backend, Match, MatchKind, Searcher, ThreeByteWM, TwoByteWM};
     use aho_corasick::{AcAutomaton, Automaton};
+    use std::io::Read;
+
+    /// A `Read` impl that only ever hands out `chunk_size` bytes at a time, to exercise matches
+    /// that straddle a refill boundary.
+    struct ChunkReader {
+        data: Vec<u8>,
+        pos: usize,
+        chunk_size: usize,
+    }
+
+    impl Read for ChunkReader {
+        fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+            let n = ::std::cmp::min(self.chunk_size, ::std::cmp::min(buf.len(), self.data.len() - self.pos));
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
 
     #[test]
     fn examples() {
@@ -261,6 +1279,169 @@ mod tests {
             assert_eq!(wm_answer, ac_answer);
         }
     }
+
+    #[test]
+    fn searcher_dispatches_by_needle_set() {
+        let haystack = "The quick brown fox jumped over the lazy dog.";
+
+        let single = Searcher::new(&["brown"]);
+        assert_eq!(single.find(haystack).collect::<Vec<_>>(),
+                   vec![Match { start: 10, end: 15, pat_idx: 0 }]);
+
+        let byte_set = Searcher::new(&["q", "z"]);
+        assert_eq!(byte_set.find(haystack).collect::<Vec<_>>(),
+                   vec![Match { start: 4, end: 5, pat_idx: 0 },
+                        Match { start: 38, end: 39, pat_idx: 1 }]);
+
+        let needles = vec!["fox", "brown", "vwxyz", "yz", "ijk", "ijklm"];
+        let two_byte = Searcher::new(&needles);
+        let wm = TwoByteWM::new(&needles);
+        assert_eq!(two_byte.find(haystack).collect::<Vec<_>>(),
+                   wm.find(haystack).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn searcher_with_backend_matches_auto() {
+        let needles = vec!["fox", "brown", "vwxyz", "yz", "ijk", "ijklm"];
+        let haystack = "The quick brown fox jumped over the lazy dog.";
+        let auto = Searcher::new(&needles);
+        let forced = Searcher::with_backend(&needles, Backend::TwoByte);
+        assert_eq!(auto.find(haystack).collect::<Vec<_>>(),
+                   forced.find(haystack).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn find_reader_matches_find() {
+        let needles = vec!["fox", "brown", "vwxyz", "yz", "ijk", "ijklm"];
+        let haystack = "The quick brown fox jumped over the lazy dog.";
+        let wm = TwoByteWM::new(&needles);
+        let expected: Vec<Match> = wm.find(haystack).collect();
+
+        // Feed the reader just one byte at a time, so that every multi-byte needle straddles a
+        // refill boundary at some point.
+        let reader = ChunkReader { data: haystack.as_bytes().to_vec(), pos: 0, chunk_size: 1 };
+        let found: Vec<Match> = wm.find_reader(reader).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn three_byte_wm_matches_ac_automaton() {
+        let needles = vec!["fox", "brown", "vwxyz", "ijk", "ijklm"];
+        let haystacks = vec![
+            "The quick brown fox jumped over the lazy dog.",
+            "abcdefghijklmnopqrstuvwxyz",
+        ];
+
+        let wm = ThreeByteWM::new(&needles);
+        let ac = AcAutomaton::new(&needles);
+        for hay in &haystacks {
+            let wm_answer: Vec<Match> = wm.find(hay).collect();
+            let ac_answer: Vec<Match> = ac.find(hay)
+                .map(|m| Match { start: m.start, end: m.end, pat_idx: m.pati })
+                .collect();
+            assert_eq!(wm_answer, ac_answer);
+        }
+    }
+
+    #[test]
+    fn searcher_picks_three_byte_for_large_needle_sets() {
+        // Enough distinct three-byte-or-longer needles to cross `THREE_BYTE_THRESHOLD`.
+        let needles: Vec<String> = (0u32..600)
+            .map(|i| format!("n{:04}", i))
+            .collect();
+        let haystack: String = needles[123].clone() + "---" + &needles[456];
+
+        let auto = Searcher::new(&needles);
+        match auto {
+            Searcher::ThreeByte(_) => {}
+            _ => panic!("expected Searcher::new to pick the ThreeByte backend"),
+        }
+
+        let forced = Searcher::with_backend(&needles, Backend::TwoByte);
+        assert_eq!(auto.find(&haystack).collect::<Vec<_>>(),
+                   forced.find(&haystack).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn find_overlapping_reports_every_match() {
+        let needles = vec!["he", "she", "his", "hers"];
+        let wm = TwoByteWM::new(&needles);
+
+        let mut found: Vec<Match> = wm.find_overlapping("ushers").collect();
+        found.sort_by(|a, b| (a.start, a.end).cmp(&(b.start, b.end)));
+
+        assert_eq!(found, vec![
+            Match { start: 1, end: 4, pat_idx: 1 }, // "she"
+            Match { start: 2, end: 4, pat_idx: 0 }, // "he"
+            Match { start: 2, end: 6, pat_idx: 3 }, // "hers"
+        ]);
+    }
+
+    #[test]
+    fn leftmost_longest_flips_the_tie_break() {
+        // "ijk" and "ijklm" share a prefix, so they collide in the same hash
+        // bucket and tie at the same starting position.
+        let needles = vec!["ijk", "ijklm"];
+        let wm = TwoByteWM::new(&needles);
+        let haystack = "abcdefghijklmnopqrstuvwxyz";
+
+        // By default, ties are broken in favor of the shortest match.
+        let shortest: Vec<Match> = wm.find(haystack).collect();
+        assert_eq!(shortest, vec![Match { start: 8, end: 11, pat_idx: 0 }]);
+
+        // `LeftmostLongest` breaks the same tie in favor of the longest match instead.
+        let longest: Vec<Match> =
+            wm.find_with_kind(haystack, MatchKind::LeftmostLongest).collect();
+        assert_eq!(longest, vec![Match { start: 8, end: 13, pat_idx: 1 }]);
+    }
+
+    #[test]
+    fn three_byte_wm_supports_kind_and_overlapping() {
+        // Mirrors `leftmost_longest_flips_the_tie_break` and `find_overlapping_reports_every_match`
+        // above, using a needle set long enough for `ThreeByteWM` (`pat_len` must be at least 3).
+        let needles = vec!["ijk", "ijklm"];
+        let wm = ThreeByteWM::new(&needles);
+        let haystack = "abcdefghijklmnopqrstuvwxyz";
+
+        let shortest: Vec<Match> = wm.find(haystack).collect();
+        assert_eq!(shortest, vec![Match { start: 8, end: 11, pat_idx: 0 }]);
+
+        let longest: Vec<Match> =
+            wm.find_with_kind(haystack, MatchKind::LeftmostLongest).collect();
+        assert_eq!(longest, vec![Match { start: 8, end: 13, pat_idx: 1 }]);
+
+        let mut overlapping: Vec<Match> = wm.find_overlapping(haystack).collect();
+        overlapping.sort_by(|a, b| (a.start, a.end).cmp(&(b.start, b.end)));
+        assert_eq!(overlapping, vec![
+            Match { start: 8, end: 11, pat_idx: 0 }, // "ijk"
+            Match { start: 8, end: 13, pat_idx: 1 }, // "ijklm"
+        ]);
+    }
+
+    #[test]
+    fn searcher_find_overlapping_and_kind_delegate_to_backend() {
+        let needles = vec!["ijk", "ijklm"];
+        let haystack = "abcdefghijklmnopqrstuvwxyz";
+        let searcher = Searcher::with_backend(&needles, Backend::TwoByte);
+        let wm = TwoByteWM::new(&needles);
+
+        assert_eq!(searcher.find_with_kind(haystack, MatchKind::LeftmostLongest).collect::<Vec<_>>(),
+                   wm.find_with_kind(haystack, MatchKind::LeftmostLongest).collect::<Vec<_>>());
+
+        let mut searcher_overlap: Vec<Match> = searcher.find_overlapping(haystack).collect();
+        let mut wm_overlap: Vec<Match> = wm.find_overlapping(haystack).collect();
+        searcher_overlap.sort_by(|a, b| (a.start, a.end).cmp(&(b.start, b.end)));
+        wm_overlap.sort_by(|a, b| (a.start, a.end).cmp(&(b.start, b.end)));
+        assert_eq!(searcher_overlap, wm_overlap);
+
+        // A single needle that overlaps itself is still found by `find_overlapping`.
+        let single = Searcher::new(&["aa"]);
+        assert_eq!(single.find_overlapping("aaaa").collect::<Vec<_>>(), vec![
+            Match { start: 0, end: 2, pat_idx: 0 },
+            Match { start: 1, end: 3, pat_idx: 0 },
+            Match { start: 2, end: 4, pat_idx: 0 },
+        ]);
+    }
 }
 
 